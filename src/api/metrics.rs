@@ -0,0 +1,75 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec,
+    TextEncoder,
+};
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    pub oracles_acknowledged: IntCounterVec,
+    pub oracles_answered: IntCounterVec,
+    pub specification_validation_failures: IntCounterVec,
+    pub ipfs_fetch_failures: IntCounter,
+    pub defillama_request_latency_seconds: HistogramVec,
+    pub blocks_behind_head: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            oracles_acknowledged: register_int_counter_vec!(
+                "defillama_answerer_oracles_acknowledged_total",
+                "Number of oracles successfully acknowledged and saved to the database",
+                &["chain_id"]
+            )
+            .unwrap(),
+            oracles_answered: register_int_counter_vec!(
+                "defillama_answerer_oracles_answered_total",
+                "Number of active oracles successfully answered on-chain",
+                &["chain_id"]
+            )
+            .unwrap(),
+            specification_validation_failures: register_int_counter_vec!(
+                "defillama_answerer_specification_validation_failures_total",
+                "Number of oracle specifications that failed validation",
+                &["chain_id"]
+            )
+            .unwrap(),
+            // incremented once a specification fetch exhausts its retries and
+            // gives up; individual retry attempts are not counted
+            ipfs_fetch_failures: register_int_counter!(
+                "defillama_answerer_ipfs_fetch_failures_total",
+                "Number of specification fetches from ipfs that failed after exhausting retries"
+            )
+            .unwrap(),
+            defillama_request_latency_seconds: register_histogram_vec!(
+                "defillama_answerer_defillama_request_latency_seconds",
+                "Latency of requests made to the defillama api",
+                &["chain_id"]
+            )
+            .unwrap(),
+            blocks_behind_head: register_int_gauge_vec!(
+                "defillama_answerer_blocks_behind_head",
+                "Difference between the latest observed chain head and the persisted checkpoint",
+                &["chain_id"]
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn set_blocks_behind_head(&self, chain_id: u64, head_block_number: u64, checkpoint_block_number: u64) {
+        let behind = head_block_number.saturating_sub(checkpoint_block_number);
+        self.blocks_behind_head
+            .with_label_values(&[&chain_id.to_string()])
+            .set(behind as i64);
+    }
+
+    pub fn render(&self) -> anyhow::Result<String> {
+        let metric_families = prometheus::gather();
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&metric_families, &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
@@ -0,0 +1,80 @@
+mod metrics;
+pub mod query;
+
+use std::time::Duration;
+
+use axum::{
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use diesel::{
+    r2d2::{ConnectionManager, Pool},
+    PgConnection,
+};
+use tokio::sync::watch;
+
+pub use metrics::METRICS;
+pub use query::{notify, OracleEventKind};
+
+// how long a shutdown waits for in-flight connections to close on their own
+// before forcibly dropping them - long enough for an in-flight request to
+// finish, short enough that an indefinitely open Subscribe stream can't hang
+// the process on shutdown
+const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn serve(
+    host: String,
+    port: u16,
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let router = Router::new()
+        .route("/health", get(health))
+        .route("/metrics", get(metrics_handler))
+        .route("/oracles/query", post(query::query))
+        .with_state(db_connection_pool);
+
+    let address = format!("{}:{}", host, port);
+    tracing::info!("api server listening on {}", address);
+
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    let mut shutdown_for_timeout = shutdown.clone();
+    let serve_future = axum::serve(listener, router).with_graceful_shutdown(async move {
+        let _ = shutdown.changed().await;
+        tracing::info!("api server shutting down");
+    });
+
+    tokio::select! {
+        result = serve_future => result?,
+        _ = async {
+            let _ = shutdown_for_timeout.changed().await;
+            tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT).await;
+        } => {
+            // a long-lived connection (e.g. an open Subscribe stream) is
+            // still around after the grace period: dropping `serve_future`
+            // here aborts it instead of waiting for it to close on its own
+            tracing::warn!(
+                "api server still had open connections after {:?}, forcing shutdown",
+                GRACEFUL_SHUTDOWN_TIMEOUT
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn health() -> impl IntoResponse {
+    StatusCode::OK
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    match METRICS.render() {
+        Ok(body) => (StatusCode::OK, body),
+        Err(error) => {
+            tracing::error!("could not render metrics: {:#}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::Context;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use diesel::{
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+    sql_types::{Bool, Text},
+    PgConnection,
+};
+use ethers::types::Address;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::db::{models::ActiveOracle, schema::active_oracles};
+
+// target size of each frame so a consumer can page through a large result
+// set instead of waiting for it to be buffered whole
+const DEFAULT_TARGET_FRAME_BYTES: usize = 64 * 1024;
+
+static ORACLE_EVENTS: Lazy<broadcast::Sender<OracleEvent>> = Lazy::new(|| broadcast::channel(1024).0);
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleEventKind {
+    Acknowledged,
+    Answered,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OracleEvent {
+    pub kind: OracleEventKind,
+    pub chain_id: u64,
+    pub address: Address,
+    pub measurement_timestamp: i64,
+}
+
+/// Publishes an oracle lifecycle event to every open `Subscribe` query, if
+/// any are currently listening.
+pub fn notify(kind: OracleEventKind, chain_id: u64, address: Address, measurement_timestamp: i64) {
+    let _ = ORACLE_EVENTS.send(OracleEvent {
+        kind,
+        chain_id,
+        address,
+        measurement_timestamp,
+    });
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMode {
+    Snapshot,
+    Subscribe,
+}
+
+/// Strips an optional `0x` prefix and lowercases the rest, so the same
+/// prefix matches an address's hex representation regardless of how the
+/// caller cased or prefixed it. Used by both the live-event matcher and the
+/// DB snapshot query, which must agree on what a prefix means.
+fn normalize_address_prefix(prefix: &str) -> String {
+    prefix.trim_start_matches("0x").trim_start_matches("0X").to_lowercase()
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct OracleQuerySelector {
+    pub chain_id: Option<u64>,
+    pub address_prefix: Option<String>,
+    pub finalized: Option<bool>,
+    pub measurement_timestamp_from: Option<i64>,
+    pub measurement_timestamp_to: Option<i64>,
+}
+
+impl OracleQuerySelector {
+    fn matches_event(&self, event: &OracleEvent) -> bool {
+        if self.finalized == Some(true) {
+            return false; // an active oracle is unfinalized by definition
+        }
+        if let Some(chain_id) = self.chain_id {
+            if chain_id != event.chain_id {
+                return false;
+            }
+        }
+        if let Some(address_prefix) = &self.address_prefix {
+            let prefix = normalize_address_prefix(address_prefix);
+            if !format!("{:x}", event.address).starts_with(&prefix) {
+                return false;
+            }
+        }
+        if let Some(from) = self.measurement_timestamp_from {
+            if event.measurement_timestamp < from {
+                return false;
+            }
+        }
+        if let Some(to) = self.measurement_timestamp_to {
+            if event.measurement_timestamp > to {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OracleQueryRequest {
+    #[serde(default)]
+    pub selector: OracleQuerySelector,
+    pub stream_mode: StreamMode,
+    #[serde(default = "default_target_frame_bytes")]
+    pub target_frame_bytes: usize,
+}
+
+fn default_target_frame_bytes() -> usize {
+    DEFAULT_TARGET_FRAME_BYTES
+}
+
+#[derive(Debug, Serialize)]
+struct ActiveOracleView {
+    chain_id: u64,
+    address: String,
+    measurement_timestamp: i64,
+    created_at_block: i64,
+}
+
+impl From<ActiveOracle> for ActiveOracleView {
+    fn from(oracle: ActiveOracle) -> Self {
+        Self {
+            chain_id: oracle.chain_id as u64,
+            address: format!("0x{:x}", oracle.address.0),
+            measurement_timestamp: oracle.measurement_timestamp,
+            created_at_block: oracle.created_at_block,
+        }
+    }
+}
+
+// rows fetched per page while streaming a snapshot, so a dashboard paging
+// through thousands of oracles only ever holds one page in memory instead
+// of the whole matching set
+const SNAPSHOT_PAGE_ROWS: i64 = 500;
+
+pub async fn query(
+    State(db_connection_pool): State<Pool<ConnectionManager<PgConnection>>>,
+    Json(request): Json<OracleQueryRequest>,
+) -> impl IntoResponse {
+    if request.selector.finalized == Some(true) {
+        return (StatusCode::OK, Body::from_stream(futures::stream::empty::<Result<Bytes, std::io::Error>>()));
+    }
+
+    let snapshot_stream = stream_matching(
+        db_connection_pool,
+        request.selector.clone(),
+        request.target_frame_bytes,
+    );
+
+    let body = match request.stream_mode {
+        StreamMode::Snapshot => Body::from_stream(snapshot_stream),
+        StreamMode::Subscribe => {
+            let selector = request.selector;
+            let live_stream = BroadcastStream::new(ORACLE_EVENTS.subscribe()).filter_map(move |event| {
+                let selector = selector.clone();
+                async move {
+                    match event {
+                        Ok(event) if selector.matches_event(&event) => {
+                            let mut line = serde_json::to_vec(&event).unwrap_or_default();
+                            line.push(b'\n');
+                            Some(Ok::<_, std::io::Error>(Bytes::from(line)))
+                        }
+                        _ => None,
+                    }
+                }
+            });
+
+            Body::from_stream(snapshot_stream.chain(live_stream))
+        }
+    };
+
+    (StatusCode::OK, body)
+}
+
+/// Streams every oracle matching `selector` as NDJSON frames, one bounded
+/// page at a time, so the response starts flowing as soon as the first page
+/// is ready instead of buffering the whole matching set in memory first.
+fn stream_matching(
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    selector: OracleQuerySelector,
+    target_frame_bytes: usize,
+) -> impl futures::Stream<Item = Result<Bytes, std::io::Error>> {
+    futures::stream::unfold(
+        (db_connection_pool, selector, None::<Address>, false),
+        move |(db_connection_pool, selector, after, done)| async move {
+            if done {
+                return None;
+            }
+
+            let page_pool = db_connection_pool.clone();
+            let page_selector = selector.clone();
+            let page = tokio::task::spawn_blocking(move || {
+                load_matching_page(&page_pool, &page_selector, after, SNAPSHOT_PAGE_ROWS)
+            })
+            .await;
+
+            let page = match page {
+                Ok(Ok(page)) => page,
+                Ok(Err(error)) => {
+                    tracing::error!("could not query active oracles: {:#}", error);
+                    return None;
+                }
+                Err(error) => {
+                    tracing::error!("active oracle query task panicked: {:#}", error);
+                    return None;
+                }
+            };
+
+            if page.is_empty() {
+                return None;
+            }
+
+            let is_last_page = (page.len() as i64) < SNAPSHOT_PAGE_ROWS;
+            let next_after = page.last().map(|oracle| oracle.address.0).or(after);
+            let views: Vec<ActiveOracleView> = page.into_iter().map(Into::into).collect();
+            let frames = chunk_into_ndjson_frames(views, target_frame_bytes);
+
+            Some((
+                futures::stream::iter(frames.into_iter().map(Ok::<_, std::io::Error>)),
+                (db_connection_pool, selector, next_after, is_last_page),
+            ))
+        },
+    )
+    .flatten()
+}
+
+/// Loads up to `limit` oracles matching `selector` whose address sorts after
+/// `after`, ordered by address so pages can be walked with a keyset cursor
+/// instead of an offset.
+fn load_matching_page(
+    db_connection_pool: &Pool<ConnectionManager<PgConnection>>,
+    selector: &OracleQuerySelector,
+    after: Option<Address>,
+    limit: i64,
+) -> anyhow::Result<Vec<ActiveOracle>> {
+    let mut db_connection = db_connection_pool
+        .get()
+        .context("could not get new connection from pool")?;
+
+    let mut query = active_oracles::table.into_boxed();
+
+    if let Some(chain_id) = selector.chain_id {
+        let chain_id = i32::try_from(chain_id).context("chain id out of range")?;
+        query = query.filter(active_oracles::dsl::chain_id.eq(chain_id));
+    }
+    if let Some(address_prefix) = &selector.address_prefix {
+        let prefix = normalize_address_prefix(address_prefix);
+        query = query.filter(
+            diesel::dsl::sql::<Bool>("address::text ILIKE ").bind::<Text, _>(format!("{}%", prefix)),
+        );
+    }
+    if let Some(from) = selector.measurement_timestamp_from {
+        query = query.filter(active_oracles::dsl::measurement_timestamp.ge(from));
+    }
+    if let Some(to) = selector.measurement_timestamp_to {
+        query = query.filter(active_oracles::dsl::measurement_timestamp.le(to));
+    }
+    if let Some(after) = after {
+        query = query.filter(
+            diesel::dsl::sql::<Bool>("address::text > ").bind::<Text, _>(format!("{:x}", after)),
+        );
+    }
+
+    query
+        .order_by(diesel::dsl::sql::<Text>("address::text"))
+        .limit(limit)
+        .select(ActiveOracle::as_select())
+        .load(&mut db_connection)
+        .context("could not load active oracles")
+}
+
+/// Splits a single page of `oracles` into NDJSON byte frames, flushing a
+/// frame once adding the next line would push it past `target_frame_bytes`.
+fn chunk_into_ndjson_frames(oracles: Vec<ActiveOracleView>, target_frame_bytes: usize) -> Vec<Bytes> {
+    let mut frames = Vec::new();
+    let mut current = Vec::new();
+
+    for oracle in oracles {
+        let mut line = serde_json::to_vec(&oracle).unwrap_or_default();
+        line.push(b'\n');
+
+        if !current.is_empty() && current.len() + line.len() > target_frame_bytes {
+            frames.push(Bytes::from(std::mem::take(&mut current)));
+        }
+        current.extend_from_slice(&line);
+    }
+
+    if !current.is_empty() {
+        frames.push(Bytes::from(current));
+    }
+
+    frames
+}
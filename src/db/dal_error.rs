@@ -0,0 +1,146 @@
+use std::{fmt, time::Instant};
+
+/// A chain id that has already been checked to fit in the `i32` column
+/// every table keys on, so call sites stop sprinkling
+/// `i32::try_from(chain_id).unwrap()` and risking a panic on a value that
+/// slipped in unchecked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChainId(i32);
+
+impl ChainId {
+    pub fn get(self) -> i32 {
+        self.0
+    }
+}
+
+impl TryFrom<u64> for ChainId {
+    type Error = ChainIdOutOfRange;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        i32::try_from(value)
+            .map(ChainId)
+            .map_err(|_| ChainIdOutOfRange(value))
+    }
+}
+
+impl fmt::Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct ChainIdOutOfRange(pub u64);
+
+impl fmt::Display for ChainIdOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chain id {} does not fit in the database's i32 chain_id column",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ChainIdOutOfRange {}
+
+/// A failed DAL call, carrying the logical operation name and the entity it
+/// touched instead of a hand-written `.context(...)` string per call site.
+#[derive(Debug)]
+pub struct DalError {
+    pub operation: &'static str,
+    pub chain_id: Option<ChainId>,
+    pub oracle_address: Option<String>,
+    pub elapsed: Option<std::time::Duration>,
+    source: anyhow::Error,
+}
+
+impl DalError {
+    pub fn new(
+        operation: &'static str,
+        chain_id: Option<ChainId>,
+        oracle_address: Option<String>,
+        source: impl Into<anyhow::Error>,
+    ) -> Self {
+        Self {
+            operation,
+            chain_id,
+            oracle_address,
+            elapsed: None,
+            source: source.into(),
+        }
+    }
+}
+
+impl fmt::Display for DalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dal operation '{}' failed", self.operation)?;
+        if let Some(chain_id) = self.chain_id {
+            write!(f, " (chain_id={})", chain_id)?;
+        }
+        if let Some(oracle_address) = &self.oracle_address {
+            write!(f, " (oracle_address={})", oracle_address)?;
+        }
+        if let Some(elapsed) = self.elapsed {
+            write!(f, " after {:?}", elapsed)?;
+        }
+        write!(f, ": {:#}", self.source)
+    }
+}
+
+impl std::error::Error for DalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl DalError {
+    /// True if the underlying failure was specifically diesel's "no row
+    /// found", as opposed to a transient connection or query error that
+    /// callers must not treat the same way (e.g. by assuming empty state).
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self.source.downcast_ref::<diesel::result::Error>(),
+            Some(diesel::result::Error::NotFound)
+        )
+    }
+}
+
+/// Runs a diesel query, turning a failure into a [`DalError`] tagged with
+/// `operation`/`chain_id`/`oracle_address` and emitting a `tracing` span
+/// covering its timing, so every DAL call gets the same structured error
+/// and span without repeating context strings at the call site.
+pub fn instrument<T>(
+    operation: &'static str,
+    chain_id: Option<ChainId>,
+    oracle_address: Option<&str>,
+    query: impl FnOnce() -> diesel::QueryResult<T>,
+) -> Result<T, DalError> {
+    let span = tracing::info_span!(
+        "dal_query",
+        operation,
+        chain_id = chain_id.map(ChainId::get),
+        oracle_address
+    );
+    let _entered = span.enter();
+
+    let started_at = Instant::now();
+    query().map_err(|error| {
+        let elapsed = started_at.elapsed();
+        tracing::error!(
+            operation,
+            chain_id = chain_id.map(ChainId::get),
+            oracle_address,
+            elapsed_ms = elapsed.as_millis() as u64,
+            error = %error,
+            "dal query failed"
+        );
+        DalError {
+            operation,
+            chain_id,
+            oracle_address: oracle_address.map(str::to_owned),
+            elapsed: Some(elapsed),
+            source: error.into(),
+        }
+    })
+}
@@ -1,20 +1,41 @@
-use anyhow::Context;
-use diesel::prelude::*;
-use ethers::types::Address;
+use std::{
+    collections::VecDeque,
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Jsonb,
+};
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
 
 use crate::specification::Specification;
 
 use super::{
-    schema::{active_oracles, snapshots},
+    dal_error::{instrument, ChainId, DalError},
+    schema::{active_oracles, jobs, snapshots},
     DbAddress,
 };
 
+// how many of the most recently seen canonical blocks are kept around per
+// chain in order to detect and unwind a reorg
+const RECENT_BLOCKS_CAPACITY: usize = 64;
+
 #[derive(Queryable, Selectable, Insertable)]
 #[diesel(table_name = active_oracles)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct ActiveOracle {
     pub address: DbAddress,
     pub chain_id: i32,
+    // the oracle's maturity date: handle_active_oracles_answering gates
+    // answering on `now >= measurement_timestamp`
+    pub measurement_timestamp: i64,
+    pub created_at_block: i64,
     pub specification: Specification,
 }
 
@@ -23,29 +44,65 @@ impl ActiveOracle {
         connection: &mut PgConnection,
         address: Address,
         chain_id: u64,
+        measurement_timestamp: SystemTime,
+        created_at_block: u64,
         specification: Specification,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("create_active_oracle", None, None, error))?;
+        let oracle_address = format!("0x{:x}", address);
+
         let oracle = ActiveOracle {
             address: DbAddress(address),
-            chain_id: i32::try_from(chain_id).unwrap(), // this should never panic
+            chain_id: chain_id.get(),
+            measurement_timestamp: measurement_timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_err(|error| {
+                    DalError::new(
+                        "create_active_oracle",
+                        Some(chain_id),
+                        Some(oracle_address.clone()),
+                        error,
+                    )
+                })?
+                .as_secs() as i64,
+            created_at_block: created_at_block as i64,
             specification,
         };
 
-        diesel::insert_into(active_oracles::table)
-            .values(&oracle)
-            .execute(connection)
-            .context("could not insert oracle into database")?;
+        // a retried acknowledge job re-sends every oracle in its batch, including
+        // ones a previous attempt already inserted before failing partway
+        // through, so this upsert has to be a no-op for rows that already exist
+        // instead of a plain insert that would dead-letter the job on a
+        // duplicate primary key
+        instrument(
+            "create_active_oracle",
+            Some(chain_id),
+            Some(&oracle_address),
+            || {
+                diesel::insert_into(active_oracles::table)
+                    .values(&oracle)
+                    .on_conflict(active_oracles::dsl::address)
+                    .do_nothing()
+                    .execute(connection)
+            },
+        )?;
 
         Ok(())
     }
 
-    pub fn delete(&self, connection: &mut PgConnection) -> anyhow::Result<()> {
-        diesel::delete(active_oracles::dsl::active_oracles.find(&self.address))
-            .execute(connection)
-            .context(format!(
-                "could not delete oracle {} from database",
-                self.address.0
-            ))?;
+    pub fn delete(&self, connection: &mut PgConnection) -> Result<(), DalError> {
+        let oracle_address = format!("0x{:x}", self.address.0);
+        let chain_id = ChainId::try_from(self.chain_id as u64).ok();
+
+        instrument(
+            "delete_active_oracle",
+            chain_id,
+            Some(&oracle_address),
+            || {
+                diesel::delete(active_oracles::dsl::active_oracles.find(&self.address)).execute(connection)
+            },
+        )?;
 
         Ok(())
     }
@@ -53,42 +110,214 @@ impl ActiveOracle {
     pub fn get_all_for_chain_id(
         connection: &mut PgConnection,
         chain_id: u64,
-    ) -> anyhow::Result<Vec<ActiveOracle>> {
-        let chain_id = i32::try_from(chain_id).unwrap(); // this should never panic
-        Ok(active_oracles::table
-            .filter(active_oracles::dsl::chain_id.eq(chain_id))
-            .select(ActiveOracle::as_select())
-            .load(connection)?)
+    ) -> Result<Vec<ActiveOracle>, DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("get_active_oracles_for_chain_id", None, None, error))?;
+
+        instrument(
+            "get_active_oracles_for_chain_id",
+            Some(chain_id),
+            None,
+            || {
+                active_oracles::table
+                    .filter(active_oracles::dsl::chain_id.eq(chain_id.get()))
+                    .select(ActiveOracle::as_select())
+                    .load(connection)
+            },
+        )
+    }
+
+    /// Deletes every oracle of `chain_id` first seen in a block at or after
+    /// `block_number`, i.e. every oracle orphaned by a reorg that rolled the
+    /// chain back to `block_number - 1`.
+    pub fn delete_created_at_or_after_block(
+        connection: &mut PgConnection,
+        chain_id: u64,
+        block_number: u64,
+    ) -> Result<usize, DalError> {
+        let chain_id = ChainId::try_from(chain_id).map_err(|error| {
+            DalError::new("delete_oracles_created_at_or_after_block", None, None, error)
+        })?;
+
+        instrument(
+            "delete_oracles_created_at_or_after_block",
+            Some(chain_id),
+            None,
+            || {
+                diesel::delete(
+                    active_oracles::dsl::active_oracles
+                        .filter(active_oracles::dsl::chain_id.eq(chain_id.get()))
+                        .filter(active_oracles::dsl::created_at_block.ge(block_number as i64)),
+                )
+                .execute(connection)
+            },
+        )
     }
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+/// A single canonical block kept in a [`Checkpoint`]'s ring buffer, used to
+/// detect when a newly observed log's parent hash no longer matches what we
+/// last persisted at that height.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentBlock {
+    pub block_number: i64,
+    pub block_hash: H256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+pub struct RecentBlocks(pub VecDeque<RecentBlock>);
+
+impl RecentBlocks {
+    fn push(&mut self, block: RecentBlock) {
+        self.0.retain(|b| b.block_number < block.block_number);
+        self.0.push_back(block);
+        while self.0.len() > RECENT_BLOCKS_CAPACITY {
+            self.0.pop_front();
+        }
+    }
+
+    fn truncate_after(&mut self, block_number: i64) {
+        self.0.retain(|b| b.block_number <= block_number);
+    }
+
+    pub fn hash_at(&self, block_number: i64) -> Option<H256> {
+        self.0
+            .iter()
+            .find(|b| b.block_number == block_number)
+            .map(|b| b.block_hash)
+    }
+
+    /// Returns the lowest block number still held in the buffer, i.e. the
+    /// deepest common ancestor we can roll back to without leaving the
+    /// buffer entirely.
+    pub fn deepest_known_ancestor(&self) -> Option<i64> {
+        self.0.iter().map(|b| b.block_number).min()
+    }
+}
+
+impl ToSql<Jsonb, Pg> for RecentBlocks {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let value = serde_json::to_value(self)?;
+        out.write_all(&[1])?; // jsonb version byte
+        out.write_all(value.to_string().as_bytes())?;
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Jsonb, Pg> for RecentBlocks {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        let raw = bytes.as_bytes();
+        let value: serde_json::Value = serde_json::from_slice(&raw[1..])?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl Default for RecentBlocks {
+    fn default() -> Self {
+        Self(VecDeque::with_capacity(RECENT_BLOCKS_CAPACITY))
+    }
+}
+
+#[derive(Queryable, Selectable, Insertable, AsChangeset)]
 #[diesel(table_name = snapshots)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct Snapshot {
+pub struct Checkpoint {
     pub chain_id: i32,
     pub block_number: i64,
+    pub recent_blocks: RecentBlocks,
 }
 
-impl Snapshot {
+impl Checkpoint {
     pub fn update(
         connection: &mut PgConnection,
         chain_id: u64,
         block_number: i64,
-    ) -> anyhow::Result<()> {
-        let chain_id: i32 = i32::try_from(chain_id).unwrap(); // this should never panic
+    ) -> Result<(), DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("update_checkpoint", None, None, error))?;
 
-        let snapshot = Snapshot {
-            chain_id,
+        let checkpoint = Checkpoint {
+            chain_id: chain_id.get(),
             block_number,
+            recent_blocks: RecentBlocks::default(),
         };
 
-        diesel::insert_into(snapshots::dsl::snapshots)
-            .values(&snapshot)
-            .on_conflict(snapshots::dsl::chain_id)
-            .do_update()
-            .set(snapshots::dsl::block_number.eq(block_number))
-            .execute(connection)?;
+        instrument("update_checkpoint", Some(chain_id), None, || {
+            diesel::insert_into(snapshots::dsl::snapshots)
+                .values(&checkpoint)
+                .on_conflict(snapshots::dsl::chain_id)
+                .do_update()
+                .set(snapshots::dsl::block_number.eq(block_number))
+                .execute(connection)
+        })?;
+
+        Ok(())
+    }
+
+    /// Records a newly observed canonical block in the chain's ring buffer,
+    /// creating the checkpoint row at `block_number` if it doesn't exist yet.
+    /// An existing row keeps its block number.
+    pub fn record_block(
+        connection: &mut PgConnection,
+        chain_id: u64,
+        block_number: u64,
+        block_hash: H256,
+    ) -> Result<RecentBlocks, DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("record_checkpoint_block", None, None, error))?;
+
+        let mut recent_blocks = match Self::get_for_chain_id(connection, chain_id.get() as u64) {
+            Ok(checkpoint) => checkpoint.recent_blocks,
+            Err(error) if error.is_not_found() => RecentBlocks::default(),
+            Err(error) => return Err(error),
+        };
+        recent_blocks.push(RecentBlock {
+            block_number: block_number as i64,
+            block_hash,
+        });
+
+        let checkpoint = Checkpoint {
+            chain_id: chain_id.get(),
+            block_number: block_number as i64,
+            recent_blocks,
+        };
+
+        instrument("record_checkpoint_block", Some(chain_id), None, || {
+            diesel::insert_into(snapshots::dsl::snapshots)
+                .values(&checkpoint)
+                .on_conflict(snapshots::dsl::chain_id)
+                .do_update()
+                .set(snapshots::dsl::recent_blocks.eq(&checkpoint.recent_blocks))
+                .execute(connection)
+        })?;
+
+        Ok(checkpoint.recent_blocks)
+    }
+
+    /// Resets the checkpoint to `ancestor_block_number` after a reorg,
+    /// discarding every ring buffer entry past it.
+    pub fn rollback_to(
+        connection: &mut PgConnection,
+        chain_id: u64,
+        ancestor_block_number: u64,
+    ) -> Result<(), DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("rollback_checkpoint", None, None, error))?;
+
+        let mut checkpoint = Self::get_for_chain_id(connection, chain_id.get() as u64)?;
+        checkpoint.block_number = ancestor_block_number as i64;
+        checkpoint
+            .recent_blocks
+            .truncate_after(ancestor_block_number as i64);
+
+        instrument("rollback_checkpoint", Some(chain_id), None, || {
+            diesel::update(
+                snapshots::dsl::snapshots.filter(snapshots::dsl::chain_id.eq(chain_id.get())),
+            )
+            .set(&checkpoint)
+            .execute(connection)
+        })?;
 
         Ok(())
     }
@@ -96,8 +325,214 @@ impl Snapshot {
     pub fn get_for_chain_id(
         connection: &mut PgConnection,
         chain_id: u64,
-    ) -> anyhow::Result<Snapshot> {
-        let chain_id = i32::try_from(chain_id).unwrap(); // this should never panic
-        Ok(snapshots::dsl::snapshots.find(chain_id).first(connection)?)
+    ) -> Result<Checkpoint, DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("get_checkpoint_for_chain_id", None, None, error))?;
+
+        instrument("get_checkpoint_for_chain_id", Some(chain_id), None, || {
+            snapshots::dsl::snapshots.find(chain_id.get()).first(connection)
+        })
+    }
+}
+
+/// The kind of work a [`Job`] carries out. Stored as text so the `jobs`
+/// table stays human-readable when inspected directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    AcknowledgeOracle,
+    AnswerOracles,
+}
+
+impl JobKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::AcknowledgeOracle => "acknowledge_oracle",
+            JobKind::AnswerOracles => "answer_oracles",
+        }
+    }
+}
+
+impl std::str::FromStr for JobKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "acknowledge_oracle" => Ok(JobKind::AcknowledgeOracle),
+            "answer_oracles" => Ok(JobKind::AnswerOracles),
+            other => Err(anyhow::anyhow!("unknown job kind: {}", other)),
+        }
+    }
+}
+
+const JOB_MAX_ATTEMPTS: i32 = 8;
+const JOB_BACKOFF_BASE_SECONDS: i64 = 5;
+const JOB_BACKOFF_CAP_SECONDS: i64 = 60 * 30;
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Job {
+    pub id: i64,
+    pub chain_id: i32,
+    pub kind: String,
+    pub payload_json: serde_json::Value,
+    pub attempts: i32,
+    pub run_after: chrono::NaiveDateTime,
+    pub locked_by: Option<String>,
+    pub locked_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = jobs)]
+struct NewJob {
+    chain_id: i32,
+    kind: String,
+    payload_json: serde_json::Value,
+}
+
+impl Job {
+    pub fn kind(&self) -> anyhow::Result<JobKind> {
+        self.kind.parse()
+    }
+
+    /// Durably persists a unit of work, to be picked up by a worker instead
+    /// of running fire-and-forget in the caller's task.
+    pub fn enqueue(
+        connection: &mut PgConnection,
+        chain_id: u64,
+        kind: JobKind,
+        payload_json: serde_json::Value,
+    ) -> Result<(), DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("enqueue_job", None, None, error))?;
+
+        let new_job = NewJob {
+            chain_id: chain_id.get(),
+            kind: kind.as_str().to_owned(),
+            payload_json,
+        };
+
+        instrument("enqueue_job", Some(chain_id), None, || {
+            diesel::insert_into(jobs::table)
+                .values(&new_job)
+                .execute(connection)
+        })?;
+
+        Ok(())
+    }
+
+    /// Claims up to `limit` due jobs belonging to `chain_id` for `worker_id`,
+    /// so a chain's worker pool never runs another chain's job with its own
+    /// signer/RPC provider. Rows held by another claim transaction are
+    /// skipped; claimed rows are stamped with `locked_by`/`locked_at`, but
+    /// later claims do not check that stamp, so a claimed job that is still
+    /// due can be claimed again.
+    pub fn claim_due(
+        connection: &mut PgConnection,
+        worker_id: &str,
+        chain_id: u64,
+        limit: i64,
+    ) -> Result<Vec<Job>, DalError> {
+        let chain_id = ChainId::try_from(chain_id)
+            .map_err(|error| DalError::new("claim_due_jobs", None, None, error))?;
+
+        instrument("claim_due_jobs", Some(chain_id), None, || {
+            connection.transaction(|connection| {
+                let due: Vec<Job> = diesel::sql_query(
+                    "SELECT id, chain_id, kind, payload_json, attempts, run_after, locked_by, locked_at \
+                     FROM jobs \
+                     WHERE run_after <= now() AND chain_id = $2 \
+                     ORDER BY run_after \
+                     LIMIT $1 \
+                     FOR UPDATE SKIP LOCKED",
+                )
+                .bind::<diesel::sql_types::BigInt, _>(limit)
+                .bind::<diesel::sql_types::Integer, _>(chain_id.get())
+                .load(connection)?;
+
+                let ids: Vec<i64> = due.iter().map(|job| job.id).collect();
+                diesel::update(jobs::dsl::jobs.filter(jobs::dsl::id.eq_any(&ids)))
+                    .set((
+                        jobs::dsl::locked_by.eq(worker_id),
+                        jobs::dsl::locked_at.eq(chrono::Utc::now().naive_utc()),
+                    ))
+                    .execute(connection)?;
+
+                Ok(due)
+            })
+        })
+    }
+
+    /// Removes a job after it has run to completion.
+    pub fn complete(&self, connection: &mut PgConnection) -> Result<(), DalError> {
+        let chain_id = ChainId::try_from(self.chain_id as u64).ok();
+
+        instrument("complete_job", chain_id, None, || {
+            diesel::delete(jobs::dsl::jobs.find(self.id)).execute(connection)
+        })?;
+
+        Ok(())
+    }
+
+    /// Reschedules the job with exponential backoff after a failed attempt,
+    /// or, once `JOB_MAX_ATTEMPTS` attempts have failed, unlocks it and
+    /// pushes its `run_after` to the maximum timestamp as a dead letter, so
+    /// it stops being retried without losing the record of what failed.
+    pub fn reschedule_after_failure(&self, connection: &mut PgConnection) -> Result<(), DalError> {
+        let chain_id = ChainId::try_from(self.chain_id as u64).ok();
+        let attempts = self.attempts + 1;
+
+        if attempts >= JOB_MAX_ATTEMPTS {
+            tracing::error!(
+                "job {} ({}) exceeded {} attempts, moving to dead letter",
+                self.id,
+                self.kind,
+                JOB_MAX_ATTEMPTS
+            );
+            instrument("dead_letter_job", chain_id, None, || {
+                diesel::update(jobs::dsl::jobs.find(self.id))
+                    .set((
+                        jobs::dsl::attempts.eq(attempts),
+                        jobs::dsl::locked_by.eq(Option::<String>::None),
+                        jobs::dsl::locked_at.eq(Option::<chrono::NaiveDateTime>::None),
+                        jobs::dsl::run_after.eq(chrono::NaiveDateTime::MAX),
+                    ))
+                    .execute(connection)
+            })?;
+
+            return Ok(());
+        }
+
+        let backoff_seconds =
+            (JOB_BACKOFF_BASE_SECONDS * 2i64.pow(attempts as u32)).min(JOB_BACKOFF_CAP_SECONDS);
+        let run_after = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(backoff_seconds);
+
+        instrument("reschedule_job", chain_id, None, || {
+            diesel::update(jobs::dsl::jobs.find(self.id))
+                .set((
+                    jobs::dsl::attempts.eq(attempts),
+                    jobs::dsl::locked_by.eq(Option::<String>::None),
+                    jobs::dsl::locked_at.eq(Option::<chrono::NaiveDateTime>::None),
+                    jobs::dsl::run_after.eq(run_after),
+                ))
+                .execute(connection)
+        })?;
+
+        Ok(())
+    }
+}
+
+impl QueryableByName<diesel::pg::Pg> for Job {
+    fn build<'a>(row: &impl diesel::row::NamedRow<'a, diesel::pg::Pg>) -> deserialize::Result<Self> {
+        Ok(Job {
+            id: diesel::row::NamedRow::get(row, "id")?,
+            chain_id: diesel::row::NamedRow::get(row, "chain_id")?,
+            kind: diesel::row::NamedRow::get(row, "kind")?,
+            payload_json: diesel::row::NamedRow::get(row, "payload_json")?,
+            attempts: diesel::row::NamedRow::get(row, "attempts")?,
+            run_after: diesel::row::NamedRow::get(row, "run_after")?,
+            locked_by: diesel::row::NamedRow::get(row, "locked_by")?,
+            locked_at: diesel::row::NamedRow::get(row, "locked_at")?,
+        })
     }
 }
\ No newline at end of file
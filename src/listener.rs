@@ -1,7 +1,11 @@
 mod commons;
 
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
+use anyhow::Context;
 use async_trait::async_trait;
 use diesel::{
     r2d2::{ConnectionManager, Pool},
@@ -9,17 +13,16 @@ use diesel::{
 };
 use ethers::{
     middleware::SignerMiddleware,
-    providers::{Http, Provider},
+    providers::{Http, Middleware, Provider},
     signers::LocalWallet,
-    types::Log,
+    types::{Log, H256},
 };
 use mibs::types::{Listener as MibsListener, Update};
+use tokio::sync::watch;
 
-use crate::{db::models, http_client::HttpClient};
+use crate::{api::METRICS, db::models};
 
-use self::commons::{
-    acknowledge_active_oracles, handle_active_oracles_answering, parse_kpi_token_creation_log,
-};
+use self::commons::{parse_kpi_token_creation_log, DefiLlamaOracleDataPayload};
 
 pub struct Listener {
     chain_id: u64,
@@ -27,9 +30,8 @@ pub struct Listener {
     signer: Arc<SignerMiddleware<Provider<Http>, LocalWallet>>,
     db_connection_pool: Pool<ConnectionManager<PgConnection>>,
     scanning_past: bool,
-    ipfs_http_client: Arc<HttpClient>,
-    defillama_http_client: Arc<HttpClient>,
-    web3_storage_http_client: Option<Arc<HttpClient>>,
+    latest_head_block_number: Arc<AtomicU64>,
+    shutdown: watch::Receiver<bool>,
 }
 
 impl Listener {
@@ -38,19 +40,17 @@ impl Listener {
         template_id: u64,
         signer: Arc<SignerMiddleware<Provider<Http>, LocalWallet>>,
         db_connection_pool: Pool<ConnectionManager<PgConnection>>,
-        ipfs_http_client: Arc<HttpClient>,
-        defillama_http_client: Arc<HttpClient>,
-        web3_storage_http_client: Option<Arc<HttpClient>>,
+        latest_head_block_number: Arc<AtomicU64>,
+        shutdown: watch::Receiver<bool>,
     ) -> Self {
         Self {
             chain_id,
             template_id,
             signer,
             db_connection_pool,
-            ipfs_http_client,
-            defillama_http_client,
-            web3_storage_http_client,
             scanning_past: true,
+            latest_head_block_number,
+            shutdown,
         }
     }
 
@@ -63,6 +63,22 @@ impl Listener {
             }
         };
 
+        let block_hash = match log.block_hash {
+            Some(block_hash) => block_hash,
+            None => {
+                tracing::warn!("could not get block hash from log {:?}", log);
+                return;
+            }
+        };
+
+        if let Err(error) = self.handle_reorg_if_needed(block_number, block_hash).await {
+            tracing::error!(
+                "could not check block {} for a chain reorg: {:#}",
+                block_number,
+                error
+            );
+        }
+
         let oracles_data = match parse_kpi_token_creation_log(
             self.chain_id,
             self.signer.clone(),
@@ -89,17 +105,212 @@ impl Listener {
                 oracles_data_len,
                 block_number
             );
+
+            let payloads: Vec<DefiLlamaOracleDataPayload> =
+                oracles_data.iter().map(DefiLlamaOracleDataPayload::from).collect();
+            match serde_json::to_value(&payloads) {
+                Ok(payload_json) => {
+                    if let Ok(mut db_connection) = self.db_connection_pool.get() {
+                        if let Err(error) = models::Job::enqueue(
+                            &mut db_connection,
+                            self.chain_id,
+                            models::JobKind::AcknowledgeOracle,
+                            payload_json,
+                        ) {
+                            tracing::error!("could not enqueue acknowledge job: {:#}", error);
+                        }
+                    }
+                }
+                Err(error) => {
+                    tracing::error!("could not serialize acknowledge job payload: {:#}", error)
+                }
+            }
         }
 
-        acknowledge_active_oracles(
+        let mut db_connection = match self.db_connection_pool.get() {
+            Ok(db_connection) => db_connection,
+            Err(error) => {
+                tracing::error!("could not get new connection from pool: {:#}", error);
+                return;
+            }
+        };
+        if let Err(error) = models::Checkpoint::record_block(
+            &mut db_connection,
             self.chain_id,
-            oracles_data,
-            self.db_connection_pool.clone(),
-            self.ipfs_http_client.clone(),
-            self.defillama_http_client.clone(),
-            self.web3_storage_http_client.clone(),
+            block_number,
+            block_hash,
+        ) {
+            tracing::error!(
+                "could not record canonical block {} in checkpoint: {:#}",
+                block_number,
+                error
+            );
+        }
+    }
+
+    /// Compares the incoming block's parent against the last hash we
+    /// persisted for it. A mismatch means the canonical chain changed under
+    /// us: walk back through the ring buffer until a block still matches the
+    /// live chain, delete every oracle first seen past that point, and roll
+    /// the checkpoint back to it so the next pass re-scans forward from
+    /// there.
+    async fn handle_reorg_if_needed(
+        &self,
+        block_number: u64,
+        block_hash: H256,
+    ) -> anyhow::Result<()> {
+        if block_number == 0 {
+            return Ok(());
+        }
+
+        let mut db_connection = self
+            .db_connection_pool
+            .get()
+            .context("could not get new connection from pool")?;
+
+        let checkpoint = match models::Checkpoint::get_for_chain_id(&mut db_connection, self.chain_id)
+        {
+            Ok(checkpoint) => checkpoint,
+            Err(error) if error.is_not_found() => return Ok(()), // nothing persisted yet, nothing to compare against
+            Err(error) => return Err(error).context("could not get checkpoint for chain id"),
+        };
+
+        let parent_block_number = block_number - 1;
+        let stored_parent_hash = match checkpoint.recent_blocks.hash_at(parent_block_number as i64) {
+            Some(stored_parent_hash) => stored_parent_hash,
+            None => return Ok(()), // parent not tracked, can't tell either way
+        };
+
+        let parent_block = self
+            .signer
+            .get_block(parent_block_number)
+            .await
+            .context("could not fetch parent block")?
+            .context("parent block not found")?;
+
+        if parent_block.hash == Some(stored_parent_hash) {
+            let _ = block_hash; // no reorg, the new block's parent still matches
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "chain reorg detected at block {}: parent hash mismatch",
+            block_number
+        );
+
+        let ancestor_block_number = self
+            .find_common_ancestor(&checkpoint.recent_blocks, parent_block_number)
+            .await?;
+
+        let deleted = models::ActiveOracle::delete_created_at_or_after_block(
+            &mut db_connection,
+            self.chain_id,
+            ancestor_block_number + 1,
         )
-        .await;
+        .context("could not delete oracles orphaned by reorg")?;
+        tracing::warn!(
+            "deleted {} oracle(s) orphaned by reorg, rolling checkpoint back to block {}",
+            deleted,
+            ancestor_block_number
+        );
+
+        models::Checkpoint::rollback_to(&mut db_connection, self.chain_id, ancestor_block_number)
+            .context("could not roll back checkpoint")?;
+
+        Ok(())
+    }
+
+    /// Walks backwards from `from_block_number` through the ring buffer,
+    /// re-fetching each candidate from the live chain until its hash still
+    /// matches what we persisted - that block is the common ancestor.
+    async fn find_common_ancestor(
+        &self,
+        recent_blocks: &models::RecentBlocks,
+        from_block_number: u64,
+    ) -> anyhow::Result<u64> {
+        let deepest_known_ancestor = recent_blocks.deepest_known_ancestor();
+
+        let mut candidate = from_block_number;
+        loop {
+            match recent_blocks.hash_at(candidate as i64) {
+                Some(stored_hash) => {
+                    let block = self
+                        .signer
+                        .get_block(candidate)
+                        .await
+                        .context("could not fetch candidate ancestor block")?;
+                    if block.and_then(|b| b.hash) == Some(stored_hash) {
+                        return Ok(candidate);
+                    }
+                }
+                None => {
+                    // fell off the ring buffer without finding a match: the
+                    // reorg goes deeper than `deepest_known_ancestor`, so we
+                    // can't actually verify this candidate against anything
+                    // we've persisted
+                    tracing::warn!(
+                        "reorg on chain {} is deeper than the {} blocks we keep track of (oldest tracked block: {:?}), rolling back to block {} without being able to verify it",
+                        self.chain_id,
+                        recent_blocks.0.len(),
+                        deepest_known_ancestor,
+                        candidate
+                    );
+                    return Ok(candidate);
+                }
+            }
+
+            if candidate == 0 {
+                return Ok(0);
+            }
+            candidate -= 1;
+        }
+    }
+
+    /// Records the hash of a newly observed head block in the ring buffer,
+    /// independently of whether it carries a matching log. Without this, a
+    /// stretch of blocks with no oracle creation logs would leave the ring
+    /// buffer with nothing to compare against, making reorg detection a
+    /// no-op for most of the chain's history.
+    async fn record_head_block(&self, block_number: u64) {
+        if block_number == 0 {
+            return;
+        }
+
+        let block_hash = match self.signer.get_block(block_number).await {
+            Ok(Some(block)) => match block.hash {
+                Some(block_hash) => block_hash,
+                None => return, // head block hasn't been sealed yet
+            },
+            Ok(None) => return,
+            Err(error) => {
+                tracing::error!(
+                    "could not fetch block {} to record it in checkpoint: {:#}",
+                    block_number,
+                    error
+                );
+                return;
+            }
+        };
+
+        let mut db_connection = match self.db_connection_pool.get() {
+            Ok(db_connection) => db_connection,
+            Err(error) => {
+                tracing::error!("could not get new connection from pool: {:#}", error);
+                return;
+            }
+        };
+        if let Err(error) = models::Checkpoint::record_block(
+            &mut db_connection,
+            self.chain_id,
+            block_number,
+            block_hash,
+        ) {
+            tracing::error!(
+                "could not record canonical block {} in checkpoint: {:#}",
+                block_number,
+                error
+            );
+        }
     }
 
     async fn update_checkpoint_block_number(&self, block_number: u64) {
@@ -114,13 +325,26 @@ impl Listener {
             models::Checkpoint::update(&mut db_connection, self.chain_id, block_number as i64)
         {
             tracing::error!("could not update snapshot block number - {:#}", error);
+            return;
         }
+
+        let latest_head_block_number = self.latest_head_block_number.load(Ordering::SeqCst);
+        METRICS.set_blocks_behind_head(self.chain_id, latest_head_block_number, block_number);
     }
 }
 
 #[async_trait]
 impl MibsListener for Listener {
     async fn on_update(&mut self, update: Update) {
+        if *self.shutdown.borrow() {
+            tracing::debug!(
+                "chain {}: shutting down, ignoring incoming {:?}",
+                self.chain_id,
+                update
+            );
+            return;
+        }
+
         match update {
             Update::NewLog(log) => self.on_log(log).await,
             Update::PastBatchCompleted {
@@ -143,15 +367,25 @@ impl MibsListener for Listener {
                 self.update_checkpoint_block_number(to_block).await;
             }
             Update::NewBlock(block_number) => {
-                if let Err(error) = handle_active_oracles_answering(
-                    self.chain_id,
-                    self.signer.clone(),
-                    self.db_connection_pool.clone(),
-                    self.defillama_http_client.clone(),
-                )
-                .await
-                {
-                    tracing::error!("error while handling active oracles answering: {:#}", error);
+                self.latest_head_block_number
+                    .store(block_number, Ordering::SeqCst);
+
+                self.record_head_block(block_number).await;
+
+                match self.db_connection_pool.get() {
+                    Ok(mut db_connection) => {
+                        if let Err(error) = models::Job::enqueue(
+                            &mut db_connection,
+                            self.chain_id,
+                            models::JobKind::AnswerOracles,
+                            serde_json::json!({}),
+                        ) {
+                            tracing::error!("could not enqueue answering job: {:#}", error);
+                        }
+                    }
+                    Err(error) => {
+                        tracing::error!("could not get new connection from pool: {:#}", error);
+                    }
                 }
 
                 if !self.scanning_past {
@@ -17,7 +17,7 @@ use diesel::{
     pg::PgConnection,
     r2d2::{ConnectionManager, Pool},
 };
-use tokio::task::JoinSet;
+use tokio::{sync::watch, task::JoinSet};
 
 use crate::{commons::ChainExecutionContext, http_client::HttpClient};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
@@ -49,7 +49,11 @@ async fn main() -> anyhow::Result<()> {
         ))
     });
 
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
     let mut join_set = JoinSet::new();
+    join_set.spawn(wait_for_shutdown_signal(shutdown_tx));
+
     for (chain_id, chain_config) in config.chain_configs.into_iter() {
         let ws_rpc_endpoint = chain_config.ws_rpc_endpoint.as_str();
 
@@ -71,15 +75,47 @@ async fn main() -> anyhow::Result<()> {
             factory_config: chain_config.factory,
         });
 
-        join_set.spawn(scanner::scan(execution_context));
+        join_set.spawn(scanner::scan(execution_context, shutdown_rx.clone()));
     }
 
-    join_set.spawn(api::serve(config.api.host, config.api.port));
+    join_set.spawn(api::serve(
+        config.api.host,
+        config.api.port,
+        db_connection_pool.clone(),
+        shutdown_rx.clone(),
+    ));
 
-    // wait forever unless some task stops with an error
+    // wait until every task - including the scanners and the api server -
+    // has wound down after a shutdown signal, or until one panics or is
+    // cancelled; an error returned by a task is discarded
     while let Some(res) = join_set.join_next().await {
         let _ = res.context("task unexpectedly stopped")?;
     }
 
     Ok(())
 }
+
+/// Waits for either ctrl-c or SIGTERM, then flips `shutdown_tx` so every
+/// scanner stops accepting new blocks/logs and the api server starts its
+/// own graceful shutdown. Chain scanners drain their in-flight
+/// acknowledge/answering jobs and flush a final checkpoint before this
+/// process actually exits.
+async fn wait_for_shutdown_signal(shutdown_tx: watch::Sender<bool>) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+    }
+
+    tracing::info!("shutdown signal received, draining in-flight work");
+    let _ = shutdown_tx.send(true);
+
+    Ok(())
+}
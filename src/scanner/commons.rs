@@ -13,10 +13,13 @@ use ethers::{
     contract::{EthLogDecode, Multicall},
     types::{Address, Log},
 };
+use serde::{Deserialize, Serialize};
 use tokio::task::JoinSet;
 use tracing_futures::Instrument;
 
 use crate::{
+    api,
+    api::METRICS,
     contracts::{
         defi_llama_oracle::{DefiLlamaOracle, Template},
         factory::FactoryEvents,
@@ -34,6 +37,44 @@ pub struct DefiLlamaOracleData {
     address: Address,
     measurement_timestamp: SystemTime,
     specification_cid: String,
+    created_at_block: u64,
+}
+
+/// JSON-serializable stand-in for [`DefiLlamaOracleData`], used as the
+/// payload of an acknowledge job so it can survive a trip through the
+/// `jobs` table.
+#[derive(Serialize, Deserialize)]
+pub struct DefiLlamaOracleDataPayload {
+    address: Address,
+    measurement_timestamp_secs: u64,
+    specification_cid: String,
+    created_at_block: u64,
+}
+
+impl From<&DefiLlamaOracleData> for DefiLlamaOracleDataPayload {
+    fn from(data: &DefiLlamaOracleData) -> Self {
+        Self {
+            address: data.address,
+            measurement_timestamp_secs: data
+                .measurement_timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            specification_cid: data.specification_cid.clone(),
+            created_at_block: data.created_at_block,
+        }
+    }
+}
+
+impl From<DefiLlamaOracleDataPayload> for DefiLlamaOracleData {
+    fn from(payload: DefiLlamaOracleDataPayload) -> Self {
+        Self {
+            address: payload.address,
+            measurement_timestamp: UNIX_EPOCH + Duration::from_secs(payload.measurement_timestamp_secs),
+            specification_cid: payload.specification_cid,
+            created_at_block: payload.created_at_block,
+        }
+    }
 }
 
 pub async fn parse_kpi_token_creation_logs(
@@ -62,6 +103,11 @@ pub async fn parse_kpi_token_creation_log(
     log: Log,
     oracle_template_id: u64,
 ) -> anyhow::Result<Vec<DefiLlamaOracleData>> {
+    let created_at_block = log
+        .block_number
+        .context("could not get block number from log")?
+        .as_u64();
+
     let raw_log = RawLog {
         topics: log.topics,
         data: log.data.to_vec(),
@@ -138,6 +184,7 @@ pub async fn parse_kpi_token_creation_log(
                     address: oracle_address,
                     measurement_timestamp,
                     specification_cid: specification,
+                    created_at_block,
                 });
             }
             Err(_) => {
@@ -152,6 +199,9 @@ pub async fn parse_kpi_token_creation_log(
     Ok(data)
 }
 
+/// Runs every oracle's acknowledgement concurrently and returns an error if
+/// any of them failed, so the caller's durable job gets rescheduled with
+/// backoff instead of being marked complete despite lost work.
 pub async fn acknowledge_active_oracles(
     chain_id: u64,
     oracles_data: Vec<DefiLlamaOracleData>,
@@ -159,7 +209,7 @@ pub async fn acknowledge_active_oracles(
     ipfs_http_client: Arc<HttpClient>,
     defillama_client: Arc<DefiLlamaClient>,
     web3_storage_http_client: Option<Arc<HttpClient>>,
-) {
+) -> anyhow::Result<()> {
     let mut join_set = JoinSet::new();
     for data in oracles_data.into_iter() {
         let oracle_address = format!("0x{}", data.address.to_string());
@@ -176,21 +226,28 @@ pub async fn acknowledge_active_oracles(
         );
     }
 
+    let mut first_error = None;
     while let Some(join_result) = join_set.join_next().await {
         match join_result {
-            Ok(result) => {
-                if let Err(error) = result {
-                    tracing::error!("an active oracle acknowledgement task unexpectedly stopped with an error:\n\n{:#}", error);
-                }
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                tracing::error!("an active oracle acknowledgement task failed:\n\n{:#}", error);
+                first_error.get_or_insert(error);
             }
             Err(error) => {
                 tracing::error!(
                     "an unexpected error happened while joining a task:\n\n{:#}",
                     error
                 );
+                first_error.get_or_insert(anyhow::anyhow!(error));
             }
         }
     }
+
+    match first_error {
+        Some(error) => Err(error).context("one or more oracle acknowledgements failed"),
+        None => Ok(()),
+    }
 }
 
 pub async fn acknowledge_active_oracle(
@@ -208,7 +265,18 @@ pub async fn acknowledge_active_oracle(
     .await
     {
         Ok(specification) => {
-            if !specification::validate(&specification, defillama_client).await {
+            let chain_id_label = chain_id.to_string();
+            let validation_timer = METRICS
+                .defillama_request_latency_seconds
+                .with_label_values(&[&chain_id_label])
+                .start_timer();
+            let is_valid = specification::validate(&specification, defillama_client).await;
+            validation_timer.observe_duration();
+            if !is_valid {
+                METRICS
+                    .specification_validation_failures
+                    .with_label_values(&[&chain_id_label])
+                    .inc();
                 tracing::error!("specification validation failed for oracle at address {:x}, this won't be handled", oracle_data.address);
                 return Ok(());
             }
@@ -222,6 +290,7 @@ pub async fn acknowledge_active_oracle(
                 oracle_data.address,
                 chain_id,
                 oracle_data.measurement_timestamp,
+                oracle_data.created_at_block,
                 specification,
             )
             .context("could not insert new active oracle into database")?;
@@ -235,6 +304,22 @@ pub async fn acknowledge_active_oracle(
                 .await?;
             }
 
+            METRICS
+                .oracles_acknowledged
+                .with_label_values(&[&chain_id_label])
+                .inc();
+            let measurement_timestamp_secs = oracle_data
+                .measurement_timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+            api::notify(
+                api::OracleEventKind::Acknowledged,
+                chain_id,
+                oracle_data.address,
+                measurement_timestamp_secs,
+            );
+
             tracing::info!(
                 "oracle with address 0x{:x} saved to database",
                 oracle_data.address
@@ -243,8 +328,73 @@ pub async fn acknowledge_active_oracle(
             Ok(())
         }
         Err(error) => {
+            METRICS.ipfs_fetch_failures.inc();
             tracing::error!("{:#}", error);
-            Ok(())
+            Err(error)
+        }
+    }
+}
+
+pub async fn handle_active_oracles_answering(
+    chain_id: u64,
+    signer: Arc<Signer>,
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    defillama_client: Arc<DefiLlamaClient>,
+) -> anyhow::Result<usize> {
+    let chain_id_label = chain_id.to_string();
+    let mut db_connection = db_connection_pool
+        .get()
+        .context("could not get new connection from pool")?;
+
+    let active_oracles = models::ActiveOracle::get_all_for_chain_id(&mut db_connection, chain_id)
+        .context("could not get active oracles from database")?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("system time is before the unix epoch")?
+        .as_secs() as i64;
+
+    let mut answered_count = 0;
+    for active_oracle in active_oracles {
+        if active_oracle.measurement_timestamp > now {
+            continue;
+        }
+
+        let oracle_address = active_oracle.address.0;
+        let oracle = DefiLlamaOracle::new(oracle_address, signer.clone());
+
+        let answering_timer = METRICS
+            .defillama_request_latency_seconds
+            .with_label_values(&[&chain_id_label])
+            .start_timer();
+        let answer_result = defillama_client
+            .answer(&oracle, &active_oracle.specification)
+            .await;
+        answering_timer.observe_duration();
+
+        match answer_result {
+            Ok(()) => {
+                active_oracle
+                    .delete(&mut db_connection)
+                    .context("could not delete answered oracle from database")?;
+                answered_count += 1;
+                api::notify(
+                    api::OracleEventKind::Answered,
+                    chain_id,
+                    oracle_address,
+                    active_oracle.measurement_timestamp,
+                );
+                tracing::info!("oracle with address 0x{:x} answered", oracle_address);
+            }
+            Err(error) => {
+                tracing::error!(
+                    "could not answer oracle with address 0x{:x}: {:#}",
+                    oracle_address,
+                    error
+                );
+            }
         }
     }
+
+    Ok(answered_count)
 }
@@ -0,0 +1,171 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use diesel::{
+    r2d2::{ConnectionManager, Pool},
+    PgConnection,
+};
+use tokio::sync::watch;
+
+use crate::{
+    api::METRICS,
+    db::models::{self, Job, JobKind},
+    defillama::DefiLlamaClient,
+    http_client::HttpClient,
+    signer::Signer,
+};
+
+use super::commons::{
+    acknowledge_active_oracles, handle_active_oracles_answering, DefiLlamaOracleDataPayload,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const BATCH_SIZE: i64 = 16;
+
+/// Polls the `jobs` table for work belonging to `chain_id` and runs it,
+/// rescheduling with backoff on failure instead of dropping it like the
+/// fire-and-forget `JoinSet` tasks this replaced.
+///
+/// Once `shutdown` flips, the pool stops waiting for new work but keeps
+/// claiming and running batches until no due job is left, so an in-flight
+/// acknowledge/answer never gets dropped mid-shutdown. Jobs rescheduled for
+/// a later time stay in the table for the next run.
+pub async fn run_worker_pool(
+    chain_id: u64,
+    signer: Arc<Signer>,
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    ipfs_http_client: Arc<HttpClient>,
+    defillama_http_client: Arc<DefiLlamaClient>,
+    web3_storage_http_client: Option<Arc<HttpClient>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let worker_id = format!("chain-{}-worker", chain_id);
+
+    loop {
+        let due_jobs = {
+            let mut db_connection = db_connection_pool
+                .get()
+                .context("could not get new connection from pool")?;
+            models::Job::claim_due(&mut db_connection, &worker_id, chain_id, BATCH_SIZE)
+                .context("could not claim due jobs")?
+        };
+
+        if due_jobs.is_empty() {
+            if *shutdown.borrow() {
+                tracing::info!("worker pool for chain {} drained, shutting down", chain_id);
+                return Ok(());
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = shutdown.changed() => {}
+            }
+            continue;
+        }
+
+        for job in due_jobs {
+            run_job(
+                &job,
+                chain_id,
+                signer.clone(),
+                db_connection_pool.clone(),
+                ipfs_http_client.clone(),
+                defillama_http_client.clone(),
+                web3_storage_http_client.clone(),
+            )
+            .await;
+        }
+    }
+}
+
+async fn run_job(
+    job: &Job,
+    chain_id: u64,
+    signer: Arc<Signer>,
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    ipfs_http_client: Arc<HttpClient>,
+    defillama_http_client: Arc<DefiLlamaClient>,
+    web3_storage_http_client: Option<Arc<HttpClient>>,
+) {
+    let result = match job.kind() {
+        Ok(JobKind::AcknowledgeOracle) => {
+            run_acknowledge_job(
+                job,
+                chain_id,
+                db_connection_pool.clone(),
+                ipfs_http_client,
+                defillama_http_client,
+                web3_storage_http_client,
+            )
+            .await
+        }
+        Ok(JobKind::AnswerOracles) => {
+            run_answer_job(chain_id, signer, db_connection_pool.clone(), defillama_http_client).await
+        }
+        Err(error) => Err(error),
+    };
+
+    let mut db_connection = match db_connection_pool.get() {
+        Ok(db_connection) => db_connection,
+        Err(error) => {
+            tracing::error!("could not get new connection from pool: {:#}", error);
+            return;
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            if let Err(error) = job.complete(&mut db_connection) {
+                tracing::error!("could not mark job {} as completed: {:#}", job.id, error);
+            }
+        }
+        Err(error) => {
+            tracing::error!("job {} ({}) failed: {:#}", job.id, job.kind, error);
+            if let Err(error) = job.reschedule_after_failure(&mut db_connection) {
+                tracing::error!("could not reschedule job {}: {:#}", job.id, error);
+            }
+        }
+    }
+}
+
+async fn run_acknowledge_job(
+    job: &Job,
+    chain_id: u64,
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    ipfs_http_client: Arc<HttpClient>,
+    defillama_http_client: Arc<DefiLlamaClient>,
+    web3_storage_http_client: Option<Arc<HttpClient>>,
+) -> anyhow::Result<()> {
+    let payloads: Vec<DefiLlamaOracleDataPayload> =
+        serde_json::from_value(job.payload_json.clone())
+            .context("could not deserialize acknowledge job payload")?;
+    let oracles_data = payloads.into_iter().map(Into::into).collect();
+
+    acknowledge_active_oracles(
+        chain_id,
+        oracles_data,
+        db_connection_pool,
+        ipfs_http_client,
+        defillama_http_client,
+        web3_storage_http_client,
+    )
+    .await
+}
+
+async fn run_answer_job(
+    chain_id: u64,
+    signer: Arc<Signer>,
+    db_connection_pool: Pool<ConnectionManager<PgConnection>>,
+    defillama_http_client: Arc<DefiLlamaClient>,
+) -> anyhow::Result<()> {
+    let answered_count =
+        handle_active_oracles_answering(chain_id, signer, db_connection_pool, defillama_http_client)
+            .await?;
+
+    METRICS
+        .oracles_answered
+        .with_label_values(&[&chain_id.to_string()])
+        .inc_by(answered_count as u64);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+mod commons;
+mod jobs;
+
+use std::sync::{atomic::AtomicU64, Arc};
+
+use anyhow::Context;
+use ethers::{middleware::SignerMiddleware, providers::Provider, signers::Signer as _};
+use tokio::sync::watch;
+
+use crate::{commons::ChainExecutionContext, db::models, defillama::DefiLlamaClient, listener::Listener};
+
+/// Runs a chain's listener and job worker pool side by side until
+/// `shutdown` fires, then drains outstanding acknowledge/answer jobs and
+/// flushes a final checkpoint before returning, so a SIGTERM never leaves
+/// the checkpoint ahead of oracles that were never acknowledged.
+pub async fn scan(ctx: Arc<ChainExecutionContext>, shutdown: watch::Receiver<bool>) -> anyhow::Result<()> {
+    let provider = Provider::try_from(ctx.ws_rpc_endpoint.as_str())?;
+    let wallet = ctx
+        .answerer_private_key
+        .parse::<ethers::signers::LocalWallet>()?
+        .with_chain_id(ctx.chain_id);
+    let signer = Arc::new(SignerMiddleware::new(provider, wallet));
+
+    let latest_head_block_number = Arc::new(AtomicU64::new(0));
+
+    let listener = Listener::new(
+        ctx.chain_id,
+        ctx.template_id,
+        signer.clone(),
+        ctx.db_connection_pool.clone(),
+        latest_head_block_number.clone(),
+        shutdown.clone(),
+    );
+
+    let defillama_http_client = Arc::new(DefiLlamaClient::new());
+
+    let worker_pool = jobs::run_worker_pool(
+        ctx.chain_id,
+        signer,
+        ctx.db_connection_pool.clone(),
+        ctx.ipfs_http_client.clone(),
+        defillama_http_client,
+        ctx.web3_storage_http_client.clone(),
+        shutdown.clone(),
+    );
+
+    let mut mibs_shutdown = shutdown.clone();
+    let mibs_scan_chain_id = ctx.chain_id;
+    let mibs_scan = async move {
+        tokio::select! {
+            result = mibs::scan(listener) => result.map_err(anyhow::Error::from),
+            _ = mibs_shutdown.changed() => {
+                tracing::info!(
+                    "chain {}: shutdown signal received, no longer accepting new blocks/logs",
+                    mibs_scan_chain_id
+                );
+                Ok(())
+            }
+        }
+    };
+
+    tokio::try_join!(mibs_scan, worker_pool)?;
+
+    // outstanding acknowledge/answer jobs have now drained, so it's safe to
+    // persist the checkpoint up to the last block we saw, even if shutdown
+    // interrupted us before `on_update` could do it itself.
+    let final_block_number = latest_head_block_number.load(std::sync::atomic::Ordering::SeqCst);
+    if final_block_number > 0 {
+        let mut db_connection = ctx
+            .db_connection_pool
+            .get()
+            .context("could not get new connection from pool")?;
+        match models::Checkpoint::update(&mut db_connection, ctx.chain_id, final_block_number as i64) {
+            Ok(()) => tracing::info!(
+                "chain {}: flushed final checkpoint at block {}",
+                ctx.chain_id,
+                final_block_number
+            ),
+            Err(error) => tracing::error!(
+                "chain {}: could not flush final checkpoint: {:#}",
+                ctx.chain_id,
+                error
+            ),
+        }
+    }
+
+    Ok(())
+}